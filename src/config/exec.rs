@@ -0,0 +1,21 @@
+//! Centralizes how a child command's failure is surfaced: stream its stderr
+//! back to the caller and return a non-zero exit code, instead of the
+//! `expect`-based panics (and the Rust backtrace that comes with them) used
+//! elsewhere while building commands.
+use std::process::{Command, Stdio};
+
+/// Runs `command`, inheriting stdout/stderr so the caller sees the
+/// underlying helm/kubectl/hook output directly, and returns its exit code
+/// (defaulting to 1 if the process was killed by a signal, or if it could
+/// not even be spawned, e.g. a missing or non-executable binary/script).
+pub fn run(command: &mut Command) -> i32 {
+    command.stdout(Stdio::inherit()).stderr(Stdio::inherit());
+
+    match command.status() {
+        Ok(status) => status.code().unwrap_or(1),
+        Err(err) => {
+            eprintln!("Failed to run {:?}: {}", command, err);
+            1
+        }
+    }
+}
@@ -0,0 +1,112 @@
+//! A serde-modeled subset of the client-go kubeconfig schema, used instead of
+//! rendering a handlebars string so that an existing, possibly multi-document
+//! kubeconfig can be parsed, merged with the plugin's generated entries, and
+//! re-serialized without losing any of its structure.
+//!
+//! Every struct here only names the fields the plugin actually reads or
+//! writes; everything else (exec/auth-provider users, file-path certs,
+//! `proxy-url`, `token-file`, `extensions`, ...) is captured by a flattened
+//! `extra` map so round-tripping a real mounted kubeconfig never drops data
+//! it doesn't understand.
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_yaml;
+
+use std::collections::BTreeMap;
+
+use serde_json::Value;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KubeConfig {
+    #[serde(rename = "apiVersion")]
+    pub api_version: String,
+    pub kind: String,
+    #[serde(default)]
+    pub clusters: Vec<NamedCluster>,
+    #[serde(default)]
+    pub contexts: Vec<NamedContext>,
+    #[serde(default)]
+    pub users: Vec<NamedAuthInfo>,
+    #[serde(rename = "current-context", default)]
+    pub current_context: String,
+    #[serde(default = "empty_preferences")]
+    pub preferences: Value,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
+}
+
+fn empty_preferences() -> Value {
+    Value::Object(Default::default())
+}
+
+impl Default for KubeConfig {
+    fn default() -> KubeConfig {
+        KubeConfig {
+            api_version: "v1".to_string(),
+            kind: "Config".to_string(),
+            clusters: Vec::new(),
+            contexts: Vec::new(),
+            users: Vec::new(),
+            current_context: String::new(),
+            preferences: empty_preferences(),
+            extra: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NamedCluster {
+    pub name: String,
+    pub cluster: Cluster,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Cluster {
+    pub server: String,
+    #[serde(rename = "insecure-skip-tls-verify", skip_serializing_if = "Option::is_none")]
+    pub insecure_skip_tls_verify: Option<bool>,
+    #[serde(rename = "certificate-authority-data", skip_serializing_if = "Option::is_none")]
+    pub certificate_authority_data: Option<String>,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NamedContext {
+    pub name: String,
+    pub context: Context,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Context {
+    pub cluster: String,
+    #[serde(default)]
+    pub namespace: String,
+    pub user: String,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NamedAuthInfo {
+    pub name: String,
+    pub user: AuthInfo,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AuthInfo {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    #[serde(rename = "client-certificate-data", skip_serializing_if = "Option::is_none")]
+    pub client_certificate_data: Option<String>,
+    #[serde(rename = "client-key-data", skip_serializing_if = "Option::is_none")]
+    pub client_key_data: Option<String>,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
+}
@@ -1,43 +1,63 @@
+extern crate serde;
 extern crate serde_json;
+extern crate serde_yaml;
+extern crate base64;
 
 #[cfg(test)]
 pub mod tests;
 
+pub mod exec;
+pub mod kubeconfig;
+pub mod settings;
+
 use std::env;
 use std::fs;
 use std::fs::File;
 use std::io::Write;
-use std::collections::BTreeMap;
+use std::process;
 use std::process::Command;
 
-use handlebars::Handlebars;
+use serde::Deserialize;
 use serde_json::Value;
 use regex::Regex;
 
 use utils;
 use plugin::Plugin;
 
-const TEMPLATE: &'static str = "\
-apiVersion: v1
-clusters:
-- cluster:
-    insecure-skip-tls-verify: {{ skip_tls }}
-    server: {{ master }}
-  name: helm
-contexts:
-- context:
-    cluster: helm
-    namespace: {{ namespace }}
-    user: helm
-  name: helm
-current-context: helm
-kind: Config
-preferences: {}
-users:
-- name: helm
-  user:
-    token: {{ token }}\
-";
+use self::kubeconfig::{KubeConfig, NamedCluster, NamedContext, NamedAuthInfo, Cluster, Context,
+                        AuthInfo};
+
+const KUBECONFIG_NAME: &'static str = "helm";
+
+/// Resolves `name` to the path `Command::new` should use. Out of tests this
+/// is the `PATH`-searched binary, so a missing install surfaces as
+/// `missing_message` instead of a confusing "No such file or directory".
+/// Under test it's just `name` itself, so argument-construction tests don't
+/// depend on `helm`/`kubectl` actually being installed.
+#[cfg(not(test))]
+fn resolve_bin(name: &str, missing_message: &str) -> String {
+    utils::which(name)
+        .expect(missing_message)
+        .to_str()
+        .unwrap()
+        .to_string()
+}
+
+#[cfg(test)]
+fn resolve_bin(name: &str, _missing_message: &str) -> String {
+    name.to_string()
+}
+
+/// Renders a JSON value as a `--set-string` flag value: strings pass
+/// through as-is, everything else (numbers, bools, ...) uses its JSON
+/// literal form, so e.g. `{"replicas": 3}` doesn't panic on `as_str`.
+/// Plain `--set` values don't go through this and must be strings.
+fn value_as_flag(value: &Value) -> String {
+    match value.as_str() {
+        Some(string) => string.to_string(),
+        None => value.to_string(),
+    }
+}
 
 #[derive(Debug)]
 pub struct Config {
@@ -49,12 +69,25 @@ pub struct Config {
     pub token: Value,
     pub clean_before_release: Value,
     pub values: Value,
+    pub mode: Value,
+    pub rollback_revision: Value,
+    pub ca_data: Value,
+    pub client_cert: Value,
+    pub client_key: Value,
+    pub values_files: Value,
+    pub set_string_values: Value,
+    pub wait: Value,
+    pub atomic: Value,
+    pub timeout: Value,
+    pub dry_run: Value,
+    pub force: Value,
+    pub pre_deploy: Value,
+    pub post_deploy: Value,
 }
 
 impl Plugin for Config {
     fn build_clean_command(&self) -> Command {
-        let kubectl_bin = utils::which("kubectl").expect("Kubernetes CLI to be installed");
-        let mut command = Command::new(kubectl_bin.to_str().unwrap());
+        let mut command = Command::new(resolve_bin("kubectl", "Kubernetes CLI to be installed"));
 
         command.arg("delete").arg("jobs").arg("-l").arg(format!(
             "release={}",
@@ -66,8 +99,7 @@ impl Plugin for Config {
     }
 
     fn build_upgrade_command(&self) -> Command {
-        let helm_bin = utils::which("helm").expect("Helm to be installed");
-        let mut command = Command::new(helm_bin.to_str().unwrap());
+        let mut command = Command::new(resolve_bin("helm", "Helm to be installed"));
 
         command.arg("upgrade").arg("-i").arg(
             self.release
@@ -75,6 +107,14 @@ impl Plugin for Config {
                 .unwrap(),
         );
 
+        command.arg("--namespace").arg(
+            self.namespace.as_str().unwrap_or("default"),
+        );
+
+        for file in self.values_files.as_array().unwrap() {
+            command.arg("-f").arg(file.as_str().unwrap());
+        }
+
         for (key, value) in self.values.as_object().unwrap() {
             command.arg("--set").arg(
                 format!(
@@ -85,11 +125,161 @@ impl Plugin for Config {
             );
         }
 
+        if let Some(set_string_values) = self.set_string_values.as_object() {
+            for (key, value) in set_string_values {
+                command.arg("--set-string").arg(
+                    format!("{}={}", key, value_as_flag(value)).as_str(),
+                );
+            }
+        }
+
+        if self.wait.as_bool().unwrap_or(false) {
+            command.arg("--wait");
+        }
+
+        if self.atomic.as_bool().unwrap_or(false) {
+            command.arg("--atomic");
+        }
+
+        if let Some(timeout) = self.timeout.as_str() {
+            command.arg("--timeout").arg(timeout);
+        }
+
+        if self.force.as_bool().unwrap_or(false) {
+            command.arg("--force");
+        }
+
         command.arg(self.chart.as_str().unwrap());
         command
     }
 }
 
+impl Config {
+    pub fn build_lint_command(&self) -> Command {
+        let mut command = Command::new(resolve_bin("helm", "Helm to be installed"));
+
+        command.arg("lint").arg(self.chart.as_str().unwrap());
+        command
+    }
+
+    pub fn build_uninstall_command(&self) -> Command {
+        let mut command = Command::new(resolve_bin("helm", "Helm to be installed"));
+
+        command.arg("uninstall").arg(
+            self.release
+                .as_str()
+                .unwrap(),
+        );
+        command
+    }
+
+    pub fn build_rollback_command(&self) -> Command {
+        let mut command = Command::new(resolve_bin("helm", "Helm to be installed"));
+
+        command
+            .arg("rollback")
+            .arg(self.release.as_str().unwrap())
+            .arg(
+                self.rollback_revision
+                    .as_str()
+                    .expect("HELM_ROLLBACK_REVISION env must be set"),
+            );
+        command
+    }
+
+    pub fn build_template_command(&self) -> Command {
+        let mut command = Command::new(resolve_bin("helm", "Helm to be installed"));
+
+        command
+            .arg("template")
+            .arg(self.release.as_str().unwrap())
+            .arg(self.chart.as_str().unwrap());
+        command
+    }
+
+    /// Picks the command(s) to run for the configured `mode`, mirroring how
+    /// mature Drone Helm plugins expose a single image for multiple
+    /// lifecycle operations (lint, upgrade, uninstall, rollback, template).
+    /// An `upgrade` with `dry_run` set reuses the `template` path rather than
+    /// passing `--dry-run` through to `helm upgrade`. An unrecognized mode is
+    /// reported as an `Err` rather than panicking, so callers can surface it
+    /// as a clean non-zero exit.
+    pub fn build_commands(&self) -> Result<Vec<Command>, String> {
+        match self.mode.as_str().unwrap() {
+            "lint" => Ok(vec![self.build_lint_command()]),
+            "uninstall" | "delete" => Ok(vec![self.build_uninstall_command()]),
+            "rollback" => Ok(vec![self.build_rollback_command()]),
+            "template" => Ok(vec![self.build_template_command()]),
+            "upgrade" if self.dry_run.as_bool().unwrap_or(false) => {
+                Ok(vec![self.build_template_command()])
+            }
+            "upgrade" => {
+                if self.clean_before_release.as_bool().unwrap_or(false) {
+                    Ok(vec![self.build_clean_command(), self.build_upgrade_command()])
+                } else {
+                    Ok(vec![self.build_upgrade_command()])
+                }
+            }
+            "config_check" => Ok(vec![self.build_template_command(), self.build_lint_command()]),
+            other => Err(format!("Unknown HELM_MODE: {}", other)),
+        }
+    }
+
+    fn build_hook_command(&self, script: &str) -> Command {
+        let mut command = Command::new(script);
+
+        command
+            .env("HELM_RELEASE", self.release.as_str().unwrap())
+            .env("HELM_NAMESPACE", self.namespace.as_str().unwrap_or("default"))
+            .env("HELM_CHART", self.chart.as_str().unwrap());
+        command
+    }
+
+    /// Runs the configured `mode`'s command(s), plus the `pre_deploy`/
+    /// `post_deploy` hooks around an `upgrade`, streaming any failing child
+    /// process's stderr back to the caller and returning its exit code
+    /// instead of panicking.
+    pub fn run(&self) -> i32 {
+        let is_upgrade = self.mode.as_str().unwrap_or("upgrade") == "upgrade" &&
+            !self.dry_run.as_bool().unwrap_or(false);
+
+        if is_upgrade {
+            if let Some(script) = self.pre_deploy.as_str() {
+                let code = exec::run(&mut self.build_hook_command(script));
+                if code != 0 {
+                    return code;
+                }
+            }
+        }
+
+        let commands = match self.build_commands() {
+            Ok(commands) => commands,
+            Err(message) => {
+                eprintln!("{}", message);
+                return 1;
+            }
+        };
+
+        for mut command in commands {
+            let code = exec::run(&mut command);
+            if code != 0 {
+                return code;
+            }
+        }
+
+        if is_upgrade {
+            if let Some(script) = self.post_deploy.as_str() {
+                let code = exec::run(&mut self.build_hook_command(script));
+                if code != 0 {
+                    return code;
+                }
+            }
+        }
+
+        0
+    }
+}
+
 impl Config {
     pub fn new() -> Config {
         let mut config = Config::default();
@@ -111,53 +301,140 @@ impl Config {
             token: Value::Null,
             clean_before_release: Value::Bool(false),
             values: Value::Null,
+            mode: Value::String("upgrade".to_string()),
+            rollback_revision: Value::Null,
+            ca_data: Value::Null,
+            client_cert: Value::Null,
+            client_key: Value::Null,
+            values_files: Value::Array(Vec::new()),
+            set_string_values: Value::Null,
+            wait: Value::Bool(false),
+            atomic: Value::Bool(false),
+            timeout: Value::Null,
+            dry_run: Value::Bool(false),
+            force: Value::Bool(false),
+            pre_deploy: Value::Null,
+            post_deploy: Value::Null,
         }
     }
 
     pub fn load(&mut self) -> () {
-        self.chart = env::var("PLUGIN_CHART")
-            .or(env::var("HELM_CHART"))
-            .and_then(|chart| Ok(Value::String(chart)))
-            .expect("HELM_CHART env must be set");
-        self.master = env::var("PLUGIN_MASTER")
-            .or(env::var("HELM_MASTER"))
-            .and_then(|master| Ok(Value::String(master)))
-            .expect("HELM_MASTER env must be set");
-        self.namespace = env::var("PLUGIN_NAMESPACE")
-            .or(env::var("HELM_NAMESPACE"))
-            .and_then(|namespace| Ok(Value::String(namespace)))
+        self.chart = settings::CHART
+            .resolve()
+            .map(Value::String)
+            .expect("PLUGIN_CHART env must be set");
+        self.master = settings::MASTER
+            .resolve()
+            .map(Value::String)
+            .expect("KUBE_API_SERVER env must be set");
+        self.namespace = settings::NAMESPACE
+            .resolve()
+            .map(Value::String)
+            .unwrap_or_default();
+        self.release = settings::RELEASE
+            .resolve()
+            .map(Value::String)
+            .expect("PLUGIN_RELEASE env must be set");
+        self.skip_tls = settings::SKIP_TLS
+            .resolve()
+            .map(|skip_tls| {
+                Value::Bool(skip_tls.parse().expect("KUBE_SKIP_TLS must be bool"))
+            })
             .unwrap_or_default();
-        self.release = env::var("PLUGIN_RELEASE")
-            .or(env::var("HELM_RELEASE"))
-            .and_then(|release| Ok(Value::String(release)))
-            .expect("HELM_RELEASE env must be set");
-        self.skip_tls = env::var("PLUGIN_SKIP_TLS")
-            .or(env::var("HELM_SKIP_TLS"))
-            .and_then(|skip_tls| {
-                Ok(Value::Bool(
-                    skip_tls.parse().expect("HELM_SKIP_TLS must be bool"),
+        self.token = settings::TOKEN
+            .resolve()
+            .map(Value::String)
+            .expect("KUBE_TOKEN env must be set");
+        self.clean_before_release = settings::CLEAN_BEFORE_RELEASE
+            .resolve()
+            .map(|clean_before_release| {
+                Value::Bool(clean_before_release.parse().expect(
+                    "PLUGIN_CLEAN_BEFORE_RELEASE must be bool",
                 ))
             })
             .unwrap_or_default();
-        self.token = env::var("PLUGIN_TOKEN")
-            .or(env::var("HELM_TOKEN"))
-            .and_then(|token| Ok(Value::String(token)))
-            .expect("HELM_TOKEN env must be set");
-        self.clean_before_release = env::var("PLUGIN_CLEAN_BEFORE_RELEASE")
-            .or(env::var("HELM_CLEAN_BEFORE_RELEASE"))
-            .and_then(|clean_before_release| {
-                Ok(Value::Bool(clean_before_release.parse().expect(
-                    "HELM_CLEAN_BEFORE_RELEASE must be bool",
-                )))
+        self.mode = settings::MODE
+            .resolve()
+            .map(Value::String)
+            .unwrap_or(Value::String("upgrade".to_string()));
+        self.rollback_revision = settings::ROLLBACK_REVISION
+            .resolve()
+            .map(Value::String)
+            .unwrap_or_default();
+        self.ca_data = settings::CA_DATA
+            .resolve()
+            .map(Value::String)
+            .unwrap_or_default();
+        self.client_cert = settings::CLIENT_CERT
+            .resolve()
+            .map(Value::String)
+            .unwrap_or_default();
+        self.client_key = settings::CLIENT_KEY
+            .resolve()
+            .map(Value::String)
+            .unwrap_or_default();
+
+        if self.ca_data.is_string() && self.skip_tls.as_bool().unwrap_or(false) {
+            eprintln!("KUBE_SKIP_TLS and KUBE_CA_DATA are mutually exclusive");
+            process::exit(1);
+        }
+
+        if self.client_cert.is_string() != self.client_key.is_string() {
+            eprintln!("KUBE_CLIENT_CERT and KUBE_CLIENT_KEY must both be set together");
+            process::exit(1);
+        }
+
+        self.values_files = settings::VALUES_FILES
+            .resolve()
+            .map(|files| {
+                Value::Array(
+                    files
+                        .split(',')
+                        .filter(|file| !file.is_empty())
+                        .map(|file| Value::String(file.to_string()))
+                        .collect(),
+                )
+            })
+            .unwrap_or(Value::Array(Vec::new()));
+        self.set_string_values = settings::SET_STRING_VALUES
+            .resolve()
+            .map(|data| {
+                serde_json::from_str::<Value>(&data).expect("Failed to parse set-string values")
             })
+            .unwrap_or(Value::Null);
+        self.wait = settings::WAIT
+            .resolve()
+            .map(|wait| Value::Bool(wait.parse().expect("HELM_WAIT must be bool")))
+            .unwrap_or_default();
+        self.atomic = settings::ATOMIC
+            .resolve()
+            .map(|atomic| Value::Bool(atomic.parse().expect("HELM_ATOMIC must be bool")))
+            .unwrap_or_default();
+        self.timeout = settings::TIMEOUT
+            .resolve()
+            .map(Value::String)
+            .unwrap_or_default();
+        self.dry_run = settings::DRY_RUN
+            .resolve()
+            .map(|dry_run| Value::Bool(dry_run.parse().expect("HELM_DRY_RUN must be bool")))
+            .unwrap_or_default();
+        self.force = settings::FORCE
+            .resolve()
+            .map(|force| Value::Bool(force.parse().expect("HELM_FORCE must be bool")))
+            .unwrap_or_default();
+        self.pre_deploy = settings::PRE_DEPLOY
+            .resolve()
+            .map(Value::String)
+            .unwrap_or_default();
+        self.post_deploy = settings::POST_DEPLOY
+            .resolve()
+            .map(Value::String)
             .unwrap_or_default();
     }
 
     pub fn parse_values(&mut self) -> () {
         let regex = Regex::new(r"^\{\{(\w+)\}\}$").unwrap();
-        let data: String = env::var("PLUGIN_VALUES")
-            .or(env::var("HELM_VALUES"))
-            .unwrap_or("{}".to_string());
+        let data: String = settings::VALUES.resolve().unwrap_or("{}".to_string());
 
         self.values = serde_json::from_str::<Value>(&data).expect("Failed to parse values");
 
@@ -200,21 +477,141 @@ impl Config {
             .expect("Failed to write config");
     }
 
-    fn render_file(&self) -> String {
-        let mut handlebars = Handlebars::new();
-        let mut assigns = BTreeMap::new();
+    /// `KUBE_CA_DATA`, like `KUBE_CLIENT_CERT`/`KUBE_CLIENT_KEY`, is taken as
+    /// raw PEM and base64-encoded here to produce the `*-data` fields a
+    /// kubeconfig expects; none of the three settings take pre-encoded input.
+    fn build_helm_cluster(&self) -> NamedCluster {
+        NamedCluster {
+            name: KUBECONFIG_NAME.to_string(),
+            cluster: Cluster {
+                server: self.master.as_str().unwrap().to_string(),
+                insecure_skip_tls_verify: Some(self.skip_tls.as_bool().unwrap_or(false)),
+                certificate_authority_data: self.ca_data.as_str().map(base64::encode),
+                ..Cluster::default()
+            },
+            ..NamedCluster::default()
+        }
+    }
 
-        handlebars
-            .register_template_string("config", TEMPLATE)
-            .expect("Failed to register template");
+    fn build_helm_context(&self) -> NamedContext {
+        NamedContext {
+            name: KUBECONFIG_NAME.to_string(),
+            context: Context {
+                cluster: KUBECONFIG_NAME.to_string(),
+                namespace: self.namespace.as_str().unwrap_or("default").to_string(),
+                user: KUBECONFIG_NAME.to_string(),
+                ..Context::default()
+            },
+            ..NamedContext::default()
+        }
+    }
 
-        assigns.insert("master", &self.master);
-        assigns.insert("namespace", &self.namespace);
-        assigns.insert("skip_tls", &self.skip_tls);
-        assigns.insert("token", &self.token);
+    /// `load` rejects a `client_cert`/`client_key` pair where only one is
+    /// set, so the `_` arm here only ever means "neither is set".
+    fn build_helm_user(&self) -> NamedAuthInfo {
+        let user = match (self.client_cert.as_str(), self.client_key.as_str()) {
+            (Some(cert), Some(key)) => {
+                AuthInfo {
+                    token: None,
+                    client_certificate_data: Some(base64::encode(cert)),
+                    client_key_data: Some(base64::encode(key)),
+                    ..AuthInfo::default()
+                }
+            }
+            _ => {
+                AuthInfo {
+                    token: Some(self.token.as_str().unwrap().to_string()),
+                    ..AuthInfo::default()
+                }
+            }
+        };
 
-        handlebars.render("config", &assigns).expect(
-            "Failed to render kube config",
-        )
+        NamedAuthInfo {
+            name: KUBECONFIG_NAME.to_string(),
+            user: user,
+            ..NamedAuthInfo::default()
+        }
+    }
+
+    /// Reads every `KUBECONFIG`-listed file (colon-separated, each possibly
+    /// containing several YAML documents) and merges their `clusters`,
+    /// `contexts`, `users` and flattened `extra` fields, keeping the first
+    /// entry seen for a given name (or key) so earlier files/documents win
+    /// over later ones. `apiVersion`/`kind`/`preferences` are taken from the
+    /// first document seen, same as any other non-mergeable top-level field.
+    fn merge_kubeconfigs(paths: &str) -> KubeConfig {
+        let mut merged = KubeConfig::default();
+        let mut seen_top_level_fields = false;
+
+        for path in paths.split(':').filter(|path| !path.is_empty()) {
+            let contents = fs::read_to_string(path).expect(
+                format!("Failed to read KUBECONFIG file {}", path).as_str(),
+            );
+
+            for document in serde_yaml::Deserializer::from_str(&contents) {
+                let parsed = KubeConfig::deserialize(document).expect(
+                    format!("Failed to parse kubeconfig document in {}", path).as_str(),
+                );
+
+                if !seen_top_level_fields {
+                    merged.api_version = parsed.api_version;
+                    merged.kind = parsed.kind;
+                    merged.preferences = parsed.preferences;
+                    seen_top_level_fields = true;
+                }
+                for (key, value) in parsed.extra {
+                    merged.extra.entry(key).or_insert(value);
+                }
+
+                for cluster in parsed.clusters {
+                    if !merged.clusters.iter().any(|existing| existing.name == cluster.name) {
+                        merged.clusters.push(cluster);
+                    }
+                }
+                for context in parsed.contexts {
+                    if !merged.contexts.iter().any(|existing| existing.name == context.name) {
+                        merged.contexts.push(context);
+                    }
+                }
+                for user in parsed.users {
+                    if !merged.users.iter().any(|existing| existing.name == user.name) {
+                        merged.users.push(user);
+                    }
+                }
+                if merged.current_context.is_empty() && !parsed.current_context.is_empty() {
+                    merged.current_context = parsed.current_context;
+                }
+            }
+        }
+
+        merged
+    }
+
+    fn render_file(&self) -> String {
+        let config = match env::var("KUBECONFIG") {
+            Ok(paths) => {
+                let mut merged = Config::merge_kubeconfigs(&paths);
+
+                if merged.current_context.is_empty() {
+                    merged.clusters.push(self.build_helm_cluster());
+                    merged.contexts.push(self.build_helm_context());
+                    merged.users.push(self.build_helm_user());
+                    merged.current_context = KUBECONFIG_NAME.to_string();
+                }
+
+                merged
+            }
+            Err(_) => {
+                KubeConfig {
+                    clusters: vec![self.build_helm_cluster()],
+                    contexts: vec![self.build_helm_context()],
+                    users: vec![self.build_helm_user()],
+                    current_context: KUBECONFIG_NAME.to_string(),
+                    ..KubeConfig::default()
+                }
+            }
+        };
+
+        serde_yaml::to_string(&config).expect("Failed to render kube config")
     }
 }
@@ -0,0 +1,85 @@
+//! A single source of truth for which environment variable spellings this
+//! plugin accepts, so `Config::load` doesn't have to duplicate an
+//! `env::var("PLUGIN_X").or(env::var("HELM_X"))` chain for every field.
+use std::env;
+
+/// A setting's canonical env var name plus every accepted alias.
+/// The canonical name always wins; aliases are accepted for backward
+/// compatibility but emit a deprecation warning when used.
+pub struct Setting {
+    pub canonical: &'static str,
+    pub aliases: &'static [&'static str],
+}
+
+impl Setting {
+    pub const fn new(canonical: &'static str, aliases: &'static [&'static str]) -> Setting {
+        Setting {
+            canonical: canonical,
+            aliases: aliases,
+        }
+    }
+
+    /// Resolves the first set value, canonical name first, then aliases in
+    /// the order they're declared. Emits a deprecation warning to stderr
+    /// when an alias was used instead of the canonical name.
+    pub fn resolve(&self) -> Option<String> {
+        if let Ok(value) = env::var(self.canonical) {
+            return Some(value);
+        }
+
+        for alias in self.aliases {
+            if let Ok(value) = env::var(alias) {
+                eprintln!(
+                    "warning: {} is deprecated, use {} instead",
+                    alias,
+                    self.canonical
+                );
+                return Some(value);
+            }
+        }
+
+        None
+    }
+}
+
+// CHART/RELEASE/CLEAN_BEFORE_RELEASE/VALUES already shipped as
+// `PLUGIN_X`.or(`HELM_X`) before this module existed, so `PLUGIN_X` stays
+// canonical here: it's both the precedence existing pipelines rely on and
+// the spelling Drone itself injects from plugin settings.
+pub const CHART: Setting = Setting::new("PLUGIN_CHART", &["HELM_CHART"]);
+pub const MASTER: Setting = Setting::new("KUBE_API_SERVER", &["PLUGIN_MASTER", "HELM_MASTER"]);
+pub const NAMESPACE: Setting =
+    Setting::new("KUBE_NAMESPACE", &["PLUGIN_NAMESPACE", "HELM_NAMESPACE"]);
+pub const RELEASE: Setting = Setting::new("PLUGIN_RELEASE", &["HELM_RELEASE"]);
+pub const SKIP_TLS: Setting = Setting::new("KUBE_SKIP_TLS", &["PLUGIN_SKIP_TLS", "HELM_SKIP_TLS"]);
+pub const TOKEN: Setting = Setting::new("KUBE_TOKEN", &["PLUGIN_TOKEN", "HELM_TOKEN"]);
+pub const CLEAN_BEFORE_RELEASE: Setting = Setting::new(
+    "PLUGIN_CLEAN_BEFORE_RELEASE",
+    &["HELM_CLEAN_BEFORE_RELEASE"],
+);
+pub const VALUES: Setting = Setting::new("PLUGIN_VALUES", &["HELM_VALUES"]);
+pub const MODE: Setting = Setting::new("HELM_MODE", &["PLUGIN_MODE"]);
+pub const ROLLBACK_REVISION: Setting = Setting::new(
+    "HELM_ROLLBACK_REVISION",
+    &["PLUGIN_ROLLBACK_REVISION"],
+);
+pub const CA_DATA: Setting = Setting::new("KUBE_CA_DATA", &["PLUGIN_CA_DATA", "HELM_CA_DATA"]);
+pub const CLIENT_CERT: Setting = Setting::new(
+    "KUBE_CLIENT_CERT",
+    &["PLUGIN_CLIENT_CERT", "HELM_CLIENT_CERT"],
+);
+pub const CLIENT_KEY: Setting = Setting::new(
+    "KUBE_CLIENT_KEY",
+    &["PLUGIN_CLIENT_KEY", "HELM_CLIENT_KEY"],
+);
+pub const VALUES_FILES: Setting =
+    Setting::new("HELM_VALUES_FILES", &["PLUGIN_VALUES_FILES"]);
+pub const SET_STRING_VALUES: Setting =
+    Setting::new("HELM_SET_STRING_VALUES", &["PLUGIN_SET_STRING_VALUES"]);
+pub const WAIT: Setting = Setting::new("HELM_WAIT", &["PLUGIN_WAIT"]);
+pub const ATOMIC: Setting = Setting::new("HELM_ATOMIC", &["PLUGIN_ATOMIC"]);
+pub const TIMEOUT: Setting = Setting::new("HELM_TIMEOUT", &["PLUGIN_TIMEOUT"]);
+pub const DRY_RUN: Setting = Setting::new("HELM_DRY_RUN", &["PLUGIN_DRY_RUN"]);
+pub const FORCE: Setting = Setting::new("HELM_FORCE", &["PLUGIN_FORCE"]);
+pub const PRE_DEPLOY: Setting = Setting::new("HELM_PRE_DEPLOY", &["PLUGIN_PRE_DEPLOY"]);
+pub const POST_DEPLOY: Setting = Setting::new("HELM_POST_DEPLOY", &["PLUGIN_POST_DEPLOY"]);
@@ -0,0 +1,227 @@
+use std::fs;
+
+use serde_json::Value;
+
+use super::Config;
+use super::kubeconfig::KubeConfig;
+use super::settings::Setting;
+
+fn base_config() -> Config {
+    let mut config = Config::default();
+
+    config.chart = Value::String("stable/nginx".to_string());
+    config.release = Value::String("my-release".to_string());
+    config.master = Value::String("https://example.com".to_string());
+    config.token = Value::String("a-token".to_string());
+    config.values = Value::Object(Default::default());
+
+    config
+}
+
+#[test]
+fn build_commands_dispatches_lint_mode() {
+    let mut config = base_config();
+    config.mode = Value::String("lint".to_string());
+
+    let commands = config.build_commands().expect("lint mode should build");
+
+    assert_eq!(commands.len(), 1);
+    assert!(format!("{:?}", commands[0]).contains("\"lint\" \"stable/nginx\""));
+}
+
+#[test]
+fn build_commands_dispatches_rollback_mode() {
+    let mut config = base_config();
+    config.mode = Value::String("rollback".to_string());
+    config.rollback_revision = Value::String("4".to_string());
+
+    let commands = config.build_commands().expect("rollback mode should build");
+
+    assert_eq!(commands.len(), 1);
+    assert!(format!("{:?}", commands[0]).contains("\"rollback\" \"my-release\" \"4\""));
+}
+
+#[test]
+fn build_commands_dry_run_upgrade_reuses_template_path() {
+    let mut config = base_config();
+    config.mode = Value::String("upgrade".to_string());
+    config.dry_run = Value::Bool(true);
+
+    let commands = config.build_commands().expect("dry-run upgrade should build");
+
+    assert_eq!(commands.len(), 1);
+    assert!(format!("{:?}", commands[0]).contains("\"template\""));
+}
+
+#[test]
+fn build_commands_config_check_runs_template_then_lint() {
+    let mut config = base_config();
+    config.mode = Value::String("config_check".to_string());
+
+    let commands = config.build_commands().expect("config_check mode should build");
+
+    assert_eq!(commands.len(), 2);
+    assert!(format!("{:?}", commands[0]).contains("\"template\""));
+    assert!(format!("{:?}", commands[1]).contains("\"lint\""));
+}
+
+#[test]
+fn build_commands_rejects_unknown_mode() {
+    let mut config = base_config();
+    config.mode = Value::String("nonsense".to_string());
+
+    let result = config.build_commands();
+
+    assert!(result.is_err());
+}
+
+#[test]
+fn merge_kubeconfigs_keeps_first_cluster_for_a_duplicate_name() {
+    let first_path = std::env::temp_dir().join("drone-k8s-helm-test-first.yaml");
+    let second_path = std::env::temp_dir().join("drone-k8s-helm-test-second.yaml");
+
+    fs::write(
+        &first_path,
+        "\
+apiVersion: v1
+kind: Config
+clusters:
+- name: prod
+  cluster:
+    server: https://first.example.com
+contexts:
+- name: prod
+  context:
+    cluster: prod
+    user: prod
+current-context: prod
+users:
+- name: prod
+  user:
+    token: first-token
+",
+    ).expect("Failed to write first fixture kubeconfig");
+
+    fs::write(
+        &second_path,
+        "\
+apiVersion: v1
+kind: Config
+clusters:
+- name: prod
+  cluster:
+    server: https://second.example.com
+- name: staging
+  cluster:
+    server: https://staging.example.com
+contexts: []
+current-context: \"\"
+users: []
+",
+    ).expect("Failed to write second fixture kubeconfig");
+
+    let paths = format!(
+        "{}:{}",
+        first_path.to_str().unwrap(),
+        second_path.to_str().unwrap()
+    );
+    let merged: KubeConfig = Config::merge_kubeconfigs(&paths);
+
+    fs::remove_file(&first_path).ok();
+    fs::remove_file(&second_path).ok();
+
+    assert_eq!(merged.clusters.len(), 2);
+    let prod = merged.clusters.iter().find(|cluster| cluster.name == "prod").expect(
+        "prod cluster should be present",
+    );
+    assert_eq!(prod.cluster.server, "https://first.example.com");
+    assert_eq!(merged.current_context, "prod");
+}
+
+#[test]
+fn merge_kubeconfigs_carries_first_seen_preferences_and_extra() {
+    let path = std::env::temp_dir().join("drone-k8s-helm-test-extra.yaml");
+
+    fs::write(
+        &path,
+        "\
+apiVersion: v1
+kind: Config
+clusters: []
+contexts: []
+current-context: \"\"
+users: []
+preferences:
+  colors: true
+extensions:
+- name: my-extension
+  extension:
+    foo: bar
+",
+    ).expect("Failed to write fixture kubeconfig");
+
+    let merged: KubeConfig = Config::merge_kubeconfigs(path.to_str().unwrap());
+
+    fs::remove_file(&path).ok();
+
+    assert_eq!(merged.preferences["colors"].as_bool(), Some(true));
+    assert!(merged.extra.contains_key("extensions"));
+}
+
+#[test]
+fn kubeconfig_round_trips_exec_auth_user() {
+    let yaml = "\
+apiVersion: v1
+kind: Config
+clusters: []
+contexts: []
+current-context: \"\"
+preferences: {}
+users:
+- name: eks
+  user:
+    exec:
+      apiVersion: client.authentication.k8s.io/v1beta1
+      command: aws
+      args:
+      - eks
+      - get-token
+      - --cluster-name
+      - prod
+";
+
+    let parsed: KubeConfig = serde_yaml::from_str(yaml).expect("Failed to parse kubeconfig");
+    let rendered = serde_yaml::to_string(&parsed).expect("Failed to render kubeconfig");
+    let reparsed: KubeConfig = serde_yaml::from_str(&rendered).expect(
+        "Failed to re-parse rendered kubeconfig",
+    );
+
+    let exec_auth = reparsed.users[0].user.extra.get("exec").expect(
+        "exec auth-provider block should survive the round trip",
+    );
+    assert_eq!(exec_auth["command"].as_str(), Some("aws"));
+}
+
+#[test]
+fn setting_resolve_prefers_canonical_over_aliases() {
+    let setting = Setting::new("TEST_CANONICAL_NAME", &["TEST_ALIAS_NAME"]);
+
+    std::env::set_var("TEST_CANONICAL_NAME", "canonical");
+    std::env::set_var("TEST_ALIAS_NAME", "alias");
+
+    assert_eq!(setting.resolve(), Some("canonical".to_string()));
+
+    std::env::remove_var("TEST_CANONICAL_NAME");
+    std::env::remove_var("TEST_ALIAS_NAME");
+}
+
+#[test]
+fn setting_resolve_falls_back_to_alias() {
+    let setting = Setting::new("TEST_CANONICAL_NAME_2", &["TEST_ALIAS_NAME_2"]);
+
+    std::env::set_var("TEST_ALIAS_NAME_2", "alias");
+
+    assert_eq!(setting.resolve(), Some("alias".to_string()));
+
+    std::env::remove_var("TEST_ALIAS_NAME_2");
+}